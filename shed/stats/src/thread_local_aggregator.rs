@@ -20,13 +20,33 @@
 //! values. As for periodic aggregation - this is achieved via a future running
 //! every second thanks to tokio timer and aggregating every thread local stat.
 //! The future must be spawned on tokio in order for the aggregation to work.
+//!
+//! Stats backed by `!Send` state (e.g. thread-affine counters) cannot be
+//! registered with [`create_map`], since aggregation there may run on a
+//! different thread than the one that produced the stat. Use
+//! [`create_local_map`] and [`schedule_stats_aggregation_local`] instead,
+//! which keep aggregation confined to the thread the stats were created on.
+//!
+//! The non-local free functions (e.g. [`create_map`]) operate on a single,
+//! process-wide default [`StatsRegistry`]. Code that wants an isolated set of stats to aggregate
+//! independently - for example so tests don't interfere with one another -
+//! should create its own `StatsRegistry` instead.
+//!
+//! Aggregation doesn't have to be woken by tokio's timer either - the
+//! [`AggregationDriver`] trait abstracts over the tick source, with
+//! [`TokioIntervalDriver`] and [`ManualDriver`] provided as adapters, so
+//! callers on other executors can supply their own clock.
 
+use std::cell::RefCell;
 use std::fmt;
 use std::future::Future as NewFuture;
-use std::sync::{atomic, Arc, Mutex};
+use std::rc::{Rc, Weak as RcWeak};
+use std::sync::{atomic, Arc, Mutex, Weak};
 use std::time::{Duration, Instant};
 
-use futures::{future::ready, FutureExt as _, Stream as NewStream, StreamExt as _};
+use futures::channel::mpsc;
+use futures::future::{abortable, ready, AbortHandle};
+use futures::{FutureExt as _, Stream as NewStream, StreamExt as _};
 use futures_ext::{BoxFuture, FutureExt};
 use futures_old::Stream;
 use lazy_static::lazy_static;
@@ -35,9 +55,24 @@ use stats_traits::stats_manager::{BoxStatsManager, StatsManager};
 
 lazy_static! {
     static ref STATS_SCHEDULED: atomic::AtomicBool = atomic::AtomicBool::new(false);
-    static ref STATS_AGGREGATOR: StatsAggregator = StatsAggregator(Mutex::new(Vec::new()));
+    static ref DEFAULT_STATS_REGISTRY: Arc<StatsRegistry> = Arc::new(StatsRegistry::new());
 }
 
+thread_local! {
+    // Weak references only, mirroring `StatsRegistry`: a local map dropped by
+    // its owner is pruned here rather than kept alive/visited forever, which
+    // matters for actor/local-task workloads that create and drop many
+    // short-lived local maps per thread.
+    static LOCAL_STATS_AGGREGATOR: RefCell<Vec<RcWeak<ThreadMap<LocalStatsManager>>>> =
+        RefCell::new(Vec::new());
+}
+
+/// A `!Send` counterpart to [`BoxStatsManager`], for stats backed by
+/// thread-affine state (e.g. `Rc`-based histograms) that cannot cross thread
+/// boundaries and therefore cannot satisfy the `Send` bound required by
+/// [`create_map`] and [`schedule_stats_aggregation`].
+pub type LocalStatsManager = Box<dyn StatsManager>;
+
 /// Type alias for the future that must be spawned on tokio.
 pub type Scheduler = BoxFuture<(), tokio_old::timer::Error>;
 
@@ -81,23 +116,304 @@ impl fmt::Display for StatsScheduledErrorPreview {
 
 impl ::std::error::Error for StatsScheduledErrorPreview {}
 
-struct StatsAggregator(Mutex<Vec<Arc<ThreadMap<BoxStatsManager>>>>);
+/// An instantiable registry of `ThreadMap`s to aggregate. Holds only weak
+/// references to the maps it tracks, so a registered map that is dropped by
+/// its owner is pruned the next time the registry aggregates rather than
+/// leaking for the remainder of the process.
+///
+/// The module-level free functions (e.g. [`create_map`],
+/// [`schedule_stats_aggregation_preview`]) are a thin, process-wide wrapper
+/// around a single lazily-created default `StatsRegistry`, kept for backward
+/// compatibility. New code, and tests that want isolation from that shared
+/// global state, should create their own `StatsRegistry` instead.
+pub struct StatsRegistry {
+    maps: Mutex<Vec<Weak<ThreadMap<BoxStatsManager>>>>,
+    /// Index into `maps` where the next throttled batch should resume, see
+    /// [`StatsRegistry::aggregate_throttled`].
+    cursor: atomic::AtomicUsize,
+}
+
+impl StatsRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        StatsRegistry {
+            maps: Mutex::new(Vec::new()),
+            cursor: atomic::AtomicUsize::new(0),
+        }
+    }
+
+    /// Registers `map` for aggregation, returning a guard that unregisters it
+    /// again once dropped. The registry only keeps a weak reference, so
+    /// dropping `map` itself without dropping the guard has the same effect,
+    /// just deferred until the next [`StatsRegistry::aggregate`] call.
+    pub fn register(self: &Arc<Self>, map: &Arc<ThreadMap<BoxStatsManager>>) -> Registration {
+        let mut maps = self.maps.lock().expect("poisoned lock");
+        maps.push(Arc::downgrade(map));
+        Registration {
+            registry: Arc::downgrade(self),
+            map: Arc::downgrade(map),
+        }
+    }
+
+    /// Aggregates every still-live registered map, pruning any that have
+    /// been dropped by their owners.
+    pub fn aggregate(&self) {
+        let mut maps = self.maps.lock().expect("poisoned lock");
+        maps.retain(|map| match map.upgrade() {
+            Some(map) => {
+                map.for_each(|stats| stats.aggregate());
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// Returns a future that periodically calls [`StatsRegistry::aggregate`]
+    /// on this registry. The future must be spawned on tokio in order for the
+    /// aggregation to work.
+    pub fn schedule(self: &Arc<Self>, interval: Duration) -> SchedulerPreview {
+        self.schedule_driven(TokioIntervalDriver::new(interval))
+    }
 
-impl StatsAggregator {
-    fn aggregate(&self) {
-        let thread_maps = self.0.lock().expect("poisoned mutex");
-        for thread_map in &*thread_maps {
-            thread_map.for_each(|stats| stats.aggregate());
+    /// Like [`StatsRegistry::aggregate`], but aggregates at most `max_batch`
+    /// registered maps, resuming from where the previous call left off
+    /// instead of starting over from the beginning every time. Calling this
+    /// repeatedly still visits every registered map at least once per full
+    /// sweep (i.e. every `ceil(len / max_batch)` calls), but bounds the work
+    /// and lock-hold time of any single call. This holds while the set of
+    /// registered maps stays the same: the resume point is a plain index, so
+    /// removing a map that sits before it shifts the next map behind it, and
+    /// that map is skipped in the sweep during which the removal happens.
+    ///
+    /// The registered `Arc`s due for this batch are snapshotted while the
+    /// registry lock is held, and aggregated only after the lock has been
+    /// released, so a slow `aggregate()` on one map never blocks concurrent
+    /// registration or unregistration of others.
+    pub fn aggregate_throttled(&self, max_batch: usize) {
+        let batch = {
+            let mut maps = self.maps.lock().expect("poisoned lock");
+            maps.retain(|map| map.strong_count() > 0);
+
+            let len = maps.len();
+            if len == 0 {
+                return;
+            }
+
+            let start = self.cursor.load(atomic::Ordering::Relaxed) % len;
+            let take = max_batch.min(len);
+            let batch: Vec<_> = (0..take)
+                .filter_map(|offset| maps[(start + offset) % len].upgrade())
+                .collect();
+            self.cursor
+                .store((start + take) % len, atomic::Ordering::Relaxed);
+            batch
+        };
+
+        for map in batch {
+            map.for_each(|stats| stats.aggregate());
         }
     }
+
+    /// Returns a future that periodically calls
+    /// [`StatsRegistry::aggregate_throttled`] with `max_batch` every `quantum`.
+    /// The future must be spawned on tokio in order for the aggregation to
+    /// work.
+    pub fn schedule_throttled(
+        self: &Arc<Self>,
+        max_batch: usize,
+        quantum: Duration,
+    ) -> SchedulerPreview {
+        let this = Arc::clone(self);
+
+        TokioIntervalDriver::new(quantum)
+            .into_ticks()
+            .for_each(move |_| {
+                this.aggregate_throttled(max_batch);
+                ready(())
+            })
+            .boxed()
+    }
+
+    /// Like [`StatsRegistry::schedule`], but driven by an arbitrary
+    /// [`AggregationDriver`] tick source rather than hardcoding tokio's
+    /// timer, so callers on non-tokio executors can supply their own clock.
+    pub fn schedule_driven<D>(self: &Arc<Self>, driver: D) -> SchedulerPreview
+    where
+        D: AggregationDriver,
+    {
+        let this = Arc::clone(self);
+
+        driver
+            .into_ticks()
+            .for_each(move |_| {
+                this.aggregate();
+                ready(())
+            })
+            .boxed()
+    }
 }
 
-/// Creates the ThreadMap and registers it for periodic calls for aggregation of stats
+impl Default for StatsRegistry {
+    fn default() -> Self {
+        StatsRegistry::new()
+    }
+}
+
+/// An RAII guard returned by [`StatsRegistry::register`]. Unregisters the
+/// associated map from its registry when dropped.
+pub struct Registration {
+    registry: Weak<StatsRegistry>,
+    map: Weak<ThreadMap<BoxStatsManager>>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Some(registry) = self.registry.upgrade() {
+            let mut maps = registry.maps.lock().expect("poisoned lock");
+            maps.retain(|existing| !Weak::ptr_eq(existing, &self.map));
+        }
+    }
+}
+
+/// A source of the "ticks" that drive periodic stats aggregation, decoupling
+/// the aggregation logic from any specific async runtime. Implement this to
+/// drive aggregation from a custom scheduler - e.g. a smol-style reactor or
+/// another throttling executor - instead of tokio's timer.
+///
+/// [`TokioIntervalDriver`] adapts the existing tokio-backed timer, and
+/// [`ManualDriver`] lets tests (or any caller that wants full control) fire
+/// ticks by hand.
+pub trait AggregationDriver {
+    /// The stream of ticks; one aggregation pass is run per item yielded.
+    /// The item value itself is ignored.
+    type Ticks: NewStream + Send + 'static;
+
+    /// Consumes the driver, yielding its tick stream.
+    fn into_ticks(self) -> Self::Ticks;
+}
+
+/// An [`AggregationDriver`] backed by a tokio timer, firing a tick every
+/// `period` starting one `period` after [`AggregationDriver::into_ticks`] is
+/// called. This is the driver used
+/// internally by [`schedule_stats_aggregation_preview`],
+/// [`schedule_stats_aggregation_with`], [`schedule_stats_aggregation_local`],
+/// [`StatsRegistry::schedule`] and [`StatsRegistry::schedule_throttled`].
+pub struct TokioIntervalDriver {
+    period: Duration,
+}
+
+impl TokioIntervalDriver {
+    /// Creates a driver that ticks once every `period`.
+    pub fn new(period: Duration) -> Self {
+        TokioIntervalDriver { period }
+    }
+}
+
+impl AggregationDriver for TokioIntervalDriver {
+    type Ticks = tokio::time::Interval;
+
+    fn into_ticks(self) -> Self::Ticks {
+        let start = tokio::time::Instant::now() + self.period;
+        tokio::time::interval_at(start, self.period)
+    }
+}
+
+/// An [`AggregationDriver`] with no clock of its own; each call to
+/// [`ManualDriver::tick`] causes exactly one aggregation pass. Intended for
+/// tests and other callers that want to drive aggregation deterministically
+/// rather than off a timer.
+///
+/// Ticks can only be fired before the driver is consumed by
+/// [`AggregationDriver::into_ticks`]; the resulting stream yields the ticks
+/// queued up to then and ends once they have been drained.
+pub struct ManualDriver {
+    sender: mpsc::UnboundedSender<()>,
+    receiver: mpsc::UnboundedReceiver<()>,
+}
+
+impl ManualDriver {
+    /// Creates a driver with no pending ticks.
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::unbounded();
+        ManualDriver { sender, receiver }
+    }
+
+    /// Fires a single tick, causing one aggregation pass the next time the
+    /// driven scheduler future is polled.
+    pub fn tick(&self) {
+        // The receiving end is held by this same struct until `into_ticks`
+        // consumes it, and `tick` can't be called after that, so this send
+        // cannot fail.
+        let _ = self.sender.unbounded_send(());
+    }
+}
+
+impl Default for ManualDriver {
+    fn default() -> Self {
+        ManualDriver::new()
+    }
+}
+
+impl AggregationDriver for ManualDriver {
+    type Ticks = mpsc::UnboundedReceiver<()>;
+
+    fn into_ticks(self) -> Self::Ticks {
+        self.receiver
+    }
+}
+
+/// Like [`schedule_stats_aggregation_preview`], but driven by an arbitrary
+/// [`AggregationDriver`] tick source on the default registry instead of
+/// tokio's timer.
+///
+/// Like the other `schedule_stats_aggregation_*` functions except
+/// [`schedule_stats_aggregation_local`], this shares the same "already
+/// scheduled" flag with them, since they all drive the same default registry
+/// and running more than one of them concurrently would aggregate every
+/// `ThreadMap` multiple times per tick.
+pub fn schedule_stats_aggregation_driven<D>(
+    driver: D,
+) -> Result<SchedulerPreview, StatsScheduledErrorPreview>
+where
+    D: AggregationDriver,
+{
+    let scheduler = DEFAULT_STATS_REGISTRY.schedule_driven(driver);
+
+    if STATS_SCHEDULED.swap(true, atomic::Ordering::Relaxed) {
+        Err(StatsScheduledErrorPreview(scheduler))
+    } else {
+        Ok(scheduler)
+    }
+}
+
+/// Creates the ThreadMap and registers it with the default, process-wide
+/// [`StatsRegistry`] for periodic calls for aggregation of stats.
+///
+/// The [`Registration`] guard is leaked rather than returned, so the map
+/// cannot be unregistered explicitly; since the registry only holds a weak
+/// reference, the map is still pruned from it once every `Arc` to it has
+/// been dropped. Callers that want to unregister a map while it is still
+/// alive should create their own [`StatsRegistry`] and call
+/// [`StatsRegistry::register`] directly instead.
 pub fn create_map() -> Arc<ThreadMap<BoxStatsManager>> {
+    let map = Arc::new(ThreadMap::default());
+    std::mem::forget(DEFAULT_STATS_REGISTRY.register(&map));
+    map
+}
+
+/// Creates a `!Send` counterpart of [`create_map`] for stats backed by
+/// thread-affine state. The returned map is registered with the current
+/// thread's local aggregator list, so it is only ever visited by aggregation
+/// driven from this same thread, e.g. via
+/// [`schedule_stats_aggregation_local`].
+///
+/// Like [`StatsRegistry`], the local aggregator list only keeps a weak
+/// reference, so a map that's dropped by its owner is pruned the next time
+/// local aggregation runs rather than leaking for the life of the thread.
+pub fn create_local_map() -> Rc<ThreadMap<LocalStatsManager>> {
     let map = ThreadMap::default();
-    let map = Arc::new(map);
-    let mut vec = STATS_AGGREGATOR.0.lock().expect("poisoned lock");
-    vec.push(map.clone());
+    let map = Rc::new(map);
+    LOCAL_STATS_AGGREGATOR.with(|aggregator| aggregator.borrow_mut().push(Rc::downgrade(&map)));
     map
 }
 
@@ -147,10 +463,101 @@ pub fn schedule_stats_aggregation() -> Result<Scheduler, StatsScheduledError> {
 /// ```
 pub fn schedule_stats_aggregation_preview() -> Result<SchedulerPreview, StatsScheduledErrorPreview>
 {
-    let start = tokio::time::Instant::now() + Duration::from_secs(1);
-    let period = Duration::from_secs(1);
+    let scheduler = schedule_stats_on_stream_preview(
+        TokioIntervalDriver::new(Duration::from_secs(1)).into_ticks(),
+    );
+
+    if STATS_SCHEDULED.swap(true, atomic::Ordering::Relaxed) {
+        Err(StatsScheduledErrorPreview(scheduler))
+    } else {
+        Ok(scheduler)
+    }
+}
+
+/// A handle to a stats aggregation task scheduled via
+/// [`schedule_stats_aggregation_with`]. Dropping the handle does not stop the
+/// task; call [`AggregationHandle::stop`] explicitly to abort it.
+pub struct AggregationHandle {
+    abort_handle: AbortHandle,
+}
+
+impl AggregationHandle {
+    /// Stops the periodic aggregation task. The returned future passed to the
+    /// executor will resolve immediately after this call.
+    pub fn stop(&self) {
+        self.abort_handle.abort();
+    }
+
+    /// Triggers an immediate, out-of-band aggregation of all registered
+    /// stats, without waiting for the next scheduled tick.
+    pub fn flush_now(&self) {
+        DEFAULT_STATS_REGISTRY.aggregate();
+    }
+}
+
+/// Like [`schedule_stats_aggregation_preview`], but lets the caller configure
+/// the aggregation interval instead of hardcoding it to one second, and
+/// returns an [`AggregationHandle`] that can be used to stop the task or
+/// force an immediate flush.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use stats::schedule_stats_aggregation_with;
+/// use tokio::spawn;
+///
+/// let (scheduler, handle) = schedule_stats_aggregation_with(Duration::from_secs(5)).unwrap();
+/// spawn(scheduler);
+/// // ... later, on graceful drain:
+/// handle.stop();
+/// ```
+pub fn schedule_stats_aggregation_with(
+    interval: Duration,
+) -> Result<(SchedulerPreview, AggregationHandle), StatsScheduledErrorPreview> {
+    let scheduler =
+        schedule_stats_on_stream_preview(TokioIntervalDriver::new(interval).into_ticks());
+    let (scheduler, abort_handle) = abortable(scheduler);
+    let scheduler = scheduler.map(|_| ()).boxed();
+
+    if STATS_SCHEDULED.swap(true, atomic::Ordering::Relaxed) {
+        Err(StatsScheduledErrorPreview(scheduler))
+    } else {
+        Ok((scheduler, AggregationHandle { abort_handle }))
+    }
+}
 
-    let scheduler = schedule_stats_on_stream_preview(tokio::time::interval_at(start, period));
+/// Upon the first call to this function it will return a future that
+/// periodically aggregates at most `max_batch` of the default registry's
+/// `ThreadMap`s every `quantum`, instead of all of them on every tick. See
+/// [`StatsRegistry::aggregate_throttled`] for details.
+/// On subsequent calls it will return [`StatsScheduledErrorPreview`] that
+/// contains the future, so that the caller might still use it, but knows
+/// that it is not the first this function was called.
+///
+/// Like the other `schedule_stats_aggregation_*` functions except
+/// [`schedule_stats_aggregation_local`], this shares the same "already
+/// scheduled" flag with them, since they all drive the same default registry
+/// and running more than one of them concurrently would aggregate every
+/// `ThreadMap` multiple times per tick.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use stats::schedule_stats_aggregation_throttled;
+/// use tokio::spawn;
+///
+/// let s = schedule_stats_aggregation_throttled(50, Duration::from_millis(100)).unwrap();
+/// spawn(s);
+/// ```
+pub fn schedule_stats_aggregation_throttled(
+    max_batch: usize,
+    quantum: Duration,
+) -> Result<SchedulerPreview, StatsScheduledErrorPreview> {
+    let scheduler = DEFAULT_STATS_REGISTRY.schedule_throttled(max_batch, quantum);
 
     if STATS_SCHEDULED.swap(true, atomic::Ordering::Relaxed) {
         Err(StatsScheduledErrorPreview(scheduler))
@@ -159,6 +566,54 @@ pub fn schedule_stats_aggregation_preview() -> Result<SchedulerPreview, StatsSch
     }
 }
 
+/// Returns a `!Send` future that periodically aggregates the stats
+/// registered via [`create_local_map`] on the current thread.
+///
+/// Unlike [`schedule_stats_aggregation_preview`], this future is not
+/// required to be `Send`, so it cannot simply be `tokio::spawn`ed. Instead it
+/// must be driven with `tokio::task::spawn_local` inside a
+/// `tokio::task::LocalSet` running on a current-thread runtime, which is what
+/// makes it suitable for aggregating `!Send` thread-local stats.
+///
+/// # Examples
+///
+/// ```no_run
+/// use stats::schedule_stats_aggregation_local;
+/// use tokio::task::LocalSet;
+///
+/// # async fn doctest() {
+/// let local = LocalSet::new();
+/// local.spawn_local(schedule_stats_aggregation_local());
+/// local.await;
+/// # }
+/// ```
+pub fn schedule_stats_aggregation_local() -> impl NewFuture<Output = ()> {
+    // Capturing an `Rc` marker makes the returned future `!Send`, so it can't
+    // be moved off the thread whose local maps it aggregates.
+    let not_send = std::marker::PhantomData::<Rc<()>>;
+    TokioIntervalDriver::new(Duration::from_secs(1))
+        .into_ticks()
+        .for_each(move |_| {
+            let _not_send = &not_send;
+            aggregate_local();
+            ready(())
+        })
+}
+
+/// Aggregates every still-live map registered via [`create_local_map`] on the
+/// current thread, pruning any that have been dropped by their owners.
+fn aggregate_local() {
+    LOCAL_STATS_AGGREGATOR.with(|aggregator| {
+        aggregator.borrow_mut().retain(|map| match map.upgrade() {
+            Some(map) => {
+                map.for_each(|stats| stats.aggregate());
+                true
+            }
+            None => false,
+        });
+    });
+}
+
 /// Schedules aggregation of stats on the provided stream. This method should not
 /// be used directly, it is here for testing purposes
 #[doc(hidden)]
@@ -169,7 +624,7 @@ where
 {
     stream
         .for_each(|_| {
-            STATS_AGGREGATOR.aggregate();
+            DEFAULT_STATS_REGISTRY.aggregate();
             Ok(())
         })
         .boxify()
@@ -184,7 +639,7 @@ where
 {
     stream
         .for_each(|_| {
-            STATS_AGGREGATOR.aggregate();
+            DEFAULT_STATS_REGISTRY.aggregate();
             ready(())
         })
         .boxed()
@@ -232,4 +687,142 @@ mod tests {
 
         STATS_SCHEDULED.swap(false, atomic::Ordering::AcqRel);
     }
+
+    #[tokio::test]
+    async fn test_schedule_stats_aggregation_with() {
+        let _lock = TEST_MUTEX.lock().expect("poisoned lock");
+
+        let (scheduler, handle) = match schedule_stats_aggregation_with(Duration::from_secs(60)) {
+            Ok(pair) => pair,
+            Err(err) => panic!("Scheduler is not Ok. Reason: {:?}", err),
+        };
+
+        handle.flush_now();
+        handle.stop();
+        scheduler.await;
+
+        STATS_SCHEDULED.swap(false, atomic::Ordering::AcqRel);
+    }
+
+    #[tokio::test]
+    async fn test_schedule_stats_aggregation_throttled() {
+        let _lock = TEST_MUTEX.lock().expect("poisoned lock");
+
+        match schedule_stats_aggregation_throttled(10, Duration::from_secs(60)) {
+            Ok(_) => {}
+            Err(err) => panic!("Scheduler is not Ok. Reason: {:?}", err),
+        }
+
+        match schedule_stats_aggregation_throttled(10, Duration::from_secs(60)) {
+            Ok(_) => panic!("Scheduler should already be initialized"),
+            Err(StatsScheduledErrorPreview(_)) => {}
+        }
+
+        STATS_SCHEDULED.swap(false, atomic::Ordering::AcqRel);
+    }
+
+    #[tokio::test]
+    async fn test_create_local_map() {
+        let map = create_local_map();
+        // The map is visible from the current thread's local aggregator list,
+        // without requiring anything in it to be `Send`.
+        LOCAL_STATS_AGGREGATOR.with(|aggregator| {
+            assert_eq!(aggregator.borrow().len(), 1);
+            assert!(aggregator.borrow()[0].upgrade().is_some());
+        });
+        drop(map);
+    }
+
+    #[test]
+    fn test_create_local_map_prunes_dropped_maps() {
+        let map = create_local_map();
+        drop(map);
+
+        // The registry only holds a weak reference, so the dropped map is
+        // pruned the next time local aggregation runs instead of leaking.
+        aggregate_local();
+        LOCAL_STATS_AGGREGATOR.with(|aggregator| {
+            assert_eq!(aggregator.borrow().len(), 0);
+        });
+    }
+
+    #[test]
+    fn test_stats_registry_register_unregisters_on_drop() {
+        // An isolated registry doesn't touch global state, so unlike the
+        // tests above this one needs no TEST_MUTEX to run in parallel.
+        let registry = Arc::new(StatsRegistry::new());
+        let map = Arc::new(ThreadMap::default());
+
+        let registration = registry.register(&map);
+        assert_eq!(registry.maps.lock().expect("poisoned lock").len(), 1);
+
+        drop(registration);
+        assert_eq!(registry.maps.lock().expect("poisoned lock").len(), 0);
+    }
+
+    #[test]
+    fn test_stats_registry_aggregate_throttled_covers_full_sweep() {
+        let registry = Arc::new(StatsRegistry::new());
+        let maps: Vec<_> = (0..5).map(|_| Arc::new(ThreadMap::default())).collect();
+        let _registrations: Vec<_> = maps.iter().map(|map| registry.register(map)).collect();
+
+        // Each call aggregates at most 2 maps, so 3 calls are needed to
+        // sweep all 5 - but every map must be visited by the end of them.
+        registry.aggregate_throttled(2);
+        registry.aggregate_throttled(2);
+        registry.aggregate_throttled(2);
+
+        assert_eq!(registry.cursor.load(atomic::Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_stats_registry_prunes_dropped_maps_on_aggregate() {
+        let registry = Arc::new(StatsRegistry::new());
+        let map = Arc::new(ThreadMap::default());
+
+        let registration = registry.register(&map);
+        drop(map);
+
+        registry.aggregate();
+        assert_eq!(registry.maps.lock().expect("poisoned lock").len(), 0);
+
+        drop(registration);
+    }
+
+    #[tokio::test]
+    async fn test_stats_registry_schedule_driven_manual() {
+        let registry = Arc::new(StatsRegistry::new());
+        let map = Arc::new(ThreadMap::default());
+        let _registration = registry.register(&map);
+
+        let driver = ManualDriver::new();
+        // This extra sender is dropped before the scheduler is first polled,
+        // so it doesn't hold the tick stream open: once `schedule_driven`
+        // consumes the driver, the stream yields the two queued ticks and
+        // ends, and the scheduler completes.
+        let sender = driver.sender.clone();
+        driver.tick();
+        driver.tick();
+
+        let scheduler = registry.schedule_driven(driver);
+        drop(sender);
+        scheduler.await;
+    }
+
+    #[tokio::test]
+    async fn test_schedule_stats_aggregation_driven() {
+        let _lock = TEST_MUTEX.lock().expect("poisoned lock");
+
+        match schedule_stats_aggregation_driven(ManualDriver::new()) {
+            Ok(_) => {}
+            Err(err) => panic!("Scheduler is not Ok. Reason: {:?}", err),
+        }
+
+        match schedule_stats_aggregation_driven(ManualDriver::new()) {
+            Ok(_) => panic!("Scheduler should already be initialized"),
+            Err(StatsScheduledErrorPreview(_)) => {}
+        }
+
+        STATS_SCHEDULED.swap(false, atomic::Ordering::AcqRel);
+    }
 }